@@ -27,16 +27,112 @@ use futures::{
     prelude::*,
     stream::{FuturesUnordered, StreamExt},
 };
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::pin::Pin;
+use std::time::{Duration, Instant};
 use tower::{buffer::Buffer, service_fn, Service, ServiceExt};
-use zebra_chain::{block::BlockHeaderHash, types::BlockHeight};
+use zebra_chain::{block::BlockHeaderHash, types::BlockHeight, Network};
 
-// genesis
+// mainnet genesis
 static GENESIS: BlockHeaderHash = BlockHeaderHash([
     8, 206, 61, 151, 49, 176, 0, 192, 131, 56, 69, 92, 138, 74, 107, 208, 93, 161, 110, 38, 177,
     29, 170, 27, 145, 113, 132, 236, 232, 15, 4, 0,
 ]);
 
+// testnet genesis
+static TESTNET_GENESIS: BlockHeaderHash = BlockHeaderHash([
+    5, 166, 10, 146, 217, 157, 133, 153, 124, 206, 59, 135, 97, 108, 8, 159, 97, 36, 215, 52, 42,
+    243, 113, 6, 237, 199, 97, 38, 51, 74, 44, 4,
+]);
+
+/// The genesis block hash for `network`.
+fn genesis_hash(network: Network) -> BlockHeaderHash {
+    match network {
+        Network::Mainnet => GENESIS,
+        Network::Testnet => TESTNET_GENESIS,
+    }
+}
+
+/// Known-good block hashes at fixed heights, keyed by network. Downloaded
+/// blocks that reach one of these heights must match the recorded hash
+/// exactly, so a single flipped bit or a malicious peer is rejected instead
+/// of silently persisted.
+fn checkpoints(network: Network) -> &'static [(BlockHeight, BlockHeaderHash)] {
+    match network {
+        Network::Mainnet => &[(BlockHeight(0), GENESIS)],
+        Network::Testnet => &[(BlockHeight(0), TESTNET_GENESIS)],
+    }
+}
+
+/// `FindBlocks` returns at most this many hashes; a response shorter than
+/// this means the peer has nothing left beyond its own tip, which we treat
+/// as an observation of the current network tip height.
+const MAX_FIND_BLOCKS_RESULTS: usize = 500;
+
+/// Number of peers queried in parallel during the `ObtainTips` phase.
+const OBTAIN_TIPS_FANOUT: usize = 4;
+
+/// Starting size of the adaptive in-flight request window.
+const INITIAL_WINDOW: usize = 100;
+
+/// The window never shrinks below this, so the pipeline can always recover.
+const MIN_WINDOW: usize = 16;
+
+/// The window never grows past this, however healthy the pipeline looks.
+const MAX_WINDOW: usize = 2_000;
+
+/// Additive growth applied to the window on each successful response.
+const WINDOW_GROWTH_STEP: usize = 16;
+
+/// Multiplicative shrink applied to the window on each failed response.
+const WINDOW_SHRINK_FACTOR: f64 = 0.5;
+
+/// Smoothing factor for the per-request latency EWMA; higher values track
+/// recent samples more closely.
+const EWMA_ALPHA: f64 = 0.1;
+
+/// Grow the in-flight window additively, capped at `MAX_WINDOW`.
+fn grow_window(window: usize) -> usize {
+    (window + WINDOW_GROWTH_STEP).min(MAX_WINDOW)
+}
+
+/// Shrink the in-flight window multiplicatively, floored at `MIN_WINDOW`.
+fn shrink_window(window: usize) -> usize {
+    ((window as f64 * WINDOW_SHRINK_FACTOR) as usize).max(MIN_WINDOW)
+}
+
+/// Fold a new latency sample into the EWMA. The first sample seeds the
+/// average directly rather than blending with an arbitrary zero.
+fn update_ewma(current_ms: f64, latest_ms: f64, is_first_sample: bool) -> f64 {
+    if is_first_sample {
+        latest_ms
+    } else {
+        EWMA_ALPHA * latest_ms + (1.0 - EWMA_ALPHA) * current_ms
+    }
+}
+
+/// Heights going back from `tip`, spaced exponentially (1, 2, 4, 8, ...),
+/// down to and including height 0, most recent first. Used both to build
+/// the `ObtainTips` locator and to backfill chain history on resume, so the
+/// locator has real spacing from the very first round after a restart
+/// instead of collapsing to a single hash.
+fn exponential_heights(tip: BlockHeight) -> Vec<BlockHeight> {
+    let mut heights = Vec::new();
+    let mut height = tip.0;
+    let mut step = 1u32;
+
+    loop {
+        heights.push(BlockHeight(height));
+        if height == 0 {
+            break;
+        }
+        height = height.saturating_sub(step);
+        step = step.saturating_mul(2);
+    }
+
+    heights
+}
+
 /// `start` subcommand
 #[derive(Command, Debug, Options)]
 pub struct StartCmd {
@@ -59,21 +155,93 @@ impl StartCmd {
         );
 
         let config = app_config().network.clone();
-        let state = zebra_state::on_disk::init(zebra_state::Config::default());
+        let network = config.network;
+        let mut state = zebra_state::on_disk::init(zebra_state::Config::default());
         let (peer_set, _address_book) = zebra_network::init(config, node).await;
-        let retry_peer_set = tower::retry::Retry::new(zebra_network::RetryErrors, peer_set.clone());
+        let retry_peer_set = tower::retry::Retry::new(zebra_network::RetryErrors, peer_set);
+
+        // Resume from whatever tip we already have persisted, rather than
+        // always re-walking the chain from genesis. `Tip(None)` is the
+        // explicit "no block stored yet" signal; any other error querying
+        // an already-populated state is real and must not be swallowed.
+        let (tip, tip_height) = match state
+            .ready_and()
+            .await
+            .map_err(|e| eyre!(e))?
+            .call(zebra_state::Request::GetTip)
+            .await
+            .map_err(|e| eyre!(e))
+            .wrap_err("failed to query persisted chain tip")?
+        {
+            zebra_state::Response::Tip(Some((hash, height))) => (hash, height),
+            zebra_state::Response::Tip(None) => (genesis_hash(network), BlockHeight(0)),
+            _ => unreachable!("GetTip always gets a Tip response"),
+        };
+
+        info!(
+            ?tip,
+            height = tip_height.0,
+            "resuming sync from persisted tip"
+        );
 
         let mut downloaded_block_heights = BTreeSet::<BlockHeight>::new();
-        downloaded_block_heights.insert(BlockHeight(0));
+        downloaded_block_heights.insert(tip_height);
+
+        let mut accepted = BTreeMap::new();
+        accepted.insert(tip_height, tip);
+
+        // Backfill a few older heights so the `ObtainTips` locator has real
+        // exponential spacing from the first round after a restart, rather
+        // than collapsing to a single hash until we re-accumulate history
+        // in-session.
+        for height in exponential_heights(tip_height) {
+            if accepted.contains_key(&height) {
+                continue;
+            }
+
+            match state
+                .ready_and()
+                .await
+                .map_err(|e| eyre!(e))?
+                .call(zebra_state::Request::GetDepth { height })
+                .await
+            {
+                Ok(zebra_state::Response::Depth(Some(hash))) => {
+                    accepted.insert(height, hash);
+                }
+                Ok(zebra_state::Response::Depth(None)) => {}
+                Ok(_) => unreachable!("GetDepth always gets a Depth response"),
+                Err(e) => {
+                    // Missing history only degrades the locator's spacing
+                    // for this session; it isn't fatal to resuming sync.
+                    info!(height = height.0, error = ?e, "could not backfill locator height");
+                }
+            }
+        }
+
+        let mut known_hashes = HashSet::new();
+        known_hashes.insert(tip);
+
+        let mut tips = HashSet::new();
+        tips.insert(tip);
 
         let mut connect = Core {
             retry_peer_set,
-            peer_set,
             state,
-            tip: GENESIS,
+            network,
+            accepted,
+            tips,
+            known_hashes,
+            dead_tips: HashSet::new(),
             block_requests: FuturesUnordered::new(),
-            requested_block_heights: 0,
+            pending_blocks: BTreeMap::new(),
+            requested_block_heights: tip_height.0 as usize,
             downloaded_block_heights,
+            target_height: None,
+            window: INITIAL_WINDOW,
+            ewma_latency_ms: 0.0,
+            successes: 0,
+            failures: 0,
         };
 
         connect.run().await
@@ -123,14 +291,82 @@ where
     ZN: Service<zebra_network::Request>,
 {
     retry_peer_set: tower::retry::Retry<zebra_network::RetryErrors, ZN>,
-    peer_set: ZN,
     state: ZS,
-    tip: BlockHeaderHash,
-    block_requests: FuturesUnordered<ZN::Future>,
+    network: Network,
+    /// Our verified chain so far, keyed by height, used both to check that
+    /// newly downloaded blocks extend it and to build locators.
+    accepted: BTreeMap<BlockHeight, BlockHeaderHash>,
+    /// Candidate chain tips to extend in the next `ExtendTips` phase. More
+    /// than one can be live at once when peers disagree about the best
+    /// chain, so a competing fork is discovered instead of being hidden
+    /// behind a single locator. `accepted` still only ever holds one chain,
+    /// so this is fork *detection*: a competing tip is tracked and extended
+    /// alongside the accepted chain, but nothing here compares cumulative
+    /// work or rolls `accepted` back to switch onto a heavier fork.
+    tips: HashSet<BlockHeaderHash>,
+    /// Hashes we've already queued or downloaded, so `ObtainTips` and
+    /// `ExtendTips` don't re-request the same block twice.
+    known_hashes: HashSet<BlockHeaderHash>,
+    /// Tips whose chain failed verification, or whose `BlocksByHash` request
+    /// still failed after retries; dropped instead of being extended forever.
+    dead_tips: HashSet<BlockHeaderHash>,
+    block_requests: FuturesUnordered<TimedBlockRequest>,
+    /// Downloaded blocks waiting on their parent to be accepted, along with
+    /// the tip whose chain they extend. Blocks download concurrently via
+    /// `FuturesUnordered` with no ordering guarantee, so a later height
+    /// often arrives before the one it chains from; we hold it here instead
+    /// of failing verification permanently.
+    pending_blocks: BTreeMap<BlockHeight, PendingBlock>,
+    /// Starts at the resumed tip height and grows by every deduped hash
+    /// queued across however many concurrent tips `ObtainTips`/`ExtendTips`
+    /// are juggling, surfaced in tracing only. Not a proxy for chain height: a fork's hashes add to this
+    /// counter too, so use `chain_height()` for anything that needs the
+    /// real sync progress.
     requested_block_heights: usize,
     downloaded_block_heights: BTreeSet<BlockHeight>,
+    /// The network's current tip height, once observed from a short
+    /// `FindBlocks` response. `None` until then.
+    target_height: Option<BlockHeight>,
+    /// Current ceiling on in-flight `BlocksByHash` requests, grown on
+    /// sustained success and shrunk multiplicatively on failure.
+    window: usize,
+    /// Exponentially weighted moving average of per-request latency, in
+    /// milliseconds.
+    ewma_latency_ms: f64,
+    /// Total successful `BlocksByHash` responses, for the success rate
+    /// surfaced in tracing.
+    successes: u64,
+    /// Total failed or errored `BlocksByHash` responses.
+    failures: u64,
 }
 
+/// A downloaded block waiting on its parent, along with the tip whose chain
+/// it extends. Keying `dead_tips` by this `frontier` (rather than the
+/// block's own hash) is what lets `extend_tips` recognize and stop
+/// extending a chain that failed verification.
+struct PendingBlock {
+    frontier: BlockHeaderHash,
+    hash: BlockHeaderHash,
+    block: zebra_chain::block::Block,
+}
+
+/// A `BlocksByHash` request paired with the tip it extends and how long it
+/// took to resolve. The tip lets a failed or errored request mark the
+/// right `dead_tips` entry; the timing lets the adaptive window controller
+/// track latency without threading timers through `tower`'s `Service`
+/// interface.
+type TimedBlockRequest = Pin<
+    Box<
+        dyn Future<
+                Output = (
+                    BlockHeaderHash,
+                    Duration,
+                    Result<zebra_network::Response, Error>,
+                ),
+            > + Send,
+    >,
+>;
+
 impl<ZN, ZS> Core<ZN, ZS>
 where
     ZN: Service<zebra_network::Request, Response = zebra_network::Response, Error = Error>
@@ -147,63 +383,240 @@ where
     async fn run(&mut self) -> Result<(), Report> {
         // TODO(jlusby): Replace with real state service
 
-        while self.requested_block_heights < 700_000 {
-            let hashes = self.next_hashes().await?;
-            self.tip = *hashes.last().unwrap();
-
-            // Request the corresponding blocks in chunks
-            self.request_blocks(hashes).await?;
+        let mut shutdown: Pin<Box<dyn Future<Output = ()> + Send>> = Box::pin(wait_for_signal());
 
-            // Allow at most 300 block requests in flight.
-            self.drain_requests(300).await?;
+        // Keep syncing until we've both observed the network tip and
+        // caught up to it. Looping unconditionally while `target_height` is
+        // unknown (rather than gating on a magic-number fallback) means a
+        // node resumed past any arbitrary constant still runs `sync_step`
+        // at least once, so it gets the chance to observe the real tip.
+        // Progress is judged by `accepted`'s real chain height, not by a
+        // cumulative count of hashes queued across however many concurrent
+        // tips `ObtainTips`/`ExtendTips` happen to be juggling, since a
+        // fork's hashes would otherwise inflate that count past any real
+        // chain height.
+        while self
+            .target_height
+            .map_or(true, |target| self.chain_height() < target)
+        {
+            tokio::select! {
+                _ = &mut shutdown => {
+                    info!("shutdown signal received, stopping sync");
+                    break;
+                }
+                result = self.sync_step() => {
+                    result?;
+                }
+            }
         }
 
+        info!(
+            in_flight = self.block_requests.len(),
+            "draining in-flight block requests before shutdown"
+        );
         self.drain_requests(0).await?;
 
-        let eternity = future::pending::<()>();
-        eternity.await;
-
-        Ok(())
-    }
-
-    async fn next_hashes(&mut self) -> Result<Vec<BlockHeaderHash>, Report> {
-        // Request the next 500 hashes.
-        self.retry_peer_set
+        self.state
             .ready_and()
             .await
             .map_err(|e| eyre!(e))?
-            .call(zebra_network::Request::FindBlocks {
-                known_blocks: vec![self.tip],
-                stop: None,
-            })
+            .call(zebra_state::Request::Flush)
             .await
-            .map_err(|e| eyre!(e))
-            .wrap_err("request failed, TODO implement retry")
-            .map(|response| match response {
-                zebra_network::Response::BlockHeaderHashes(hashes) => hashes,
-                _ => unreachable!("FindBlocks always gets a BlockHeaderHashes response"),
-            })
-            .map(|hashes| {
+            .map_err(|e| eyre!(e))?;
+
+        Ok(())
+    }
+
+    /// One iteration of the sync loop: discover tips, extend them, and
+    /// drain block requests down to the current window size.
+    async fn sync_step(&mut self) -> Result<(), Report> {
+        self.obtain_tips().await?;
+        self.extend_tips().await?;
+
+        // Allow at most `self.window` block requests in flight; the window
+        // adapts to observed latency and failure rate.
+        self.drain_requests(self.window).await?;
+
+        Ok(())
+    }
+
+    /// Height of the highest block we've actually verified and accepted
+    /// into our chain. This is the authoritative measure of sync progress;
+    /// unlike a running count of hashes queued, it can't be inflated by a
+    /// competing fork's hashes or by multiple concurrent tips.
+    fn chain_height(&self) -> BlockHeight {
+        self.accepted
+            .keys()
+            .next_back()
+            .copied()
+            .unwrap_or(BlockHeight(0))
+    }
+
+    /// Build a block locator from our accepted chain: hashes at
+    /// exponentially spaced heights going back from our highest accepted
+    /// block, most recent first. Sent to peers so they can find where our
+    /// chain diverges from theirs.
+    fn locator(&self) -> Vec<BlockHeaderHash> {
+        exponential_heights(self.chain_height())
+            .into_iter()
+            .filter_map(|height| self.accepted.get(&height).copied())
+            .collect()
+    }
+
+    /// `ObtainTips` phase: ask several peers for blocks beyond an
+    /// exponential locator of our accepted chain, to discover candidate
+    /// tips we don't already know about, including ones on a fork.
+    async fn obtain_tips(&mut self) -> Result<(), Report> {
+        // The height the locator was built from, so a short response can be
+        // turned into a real observed tip height (`locator_height +
+        // hashes.len()`) instead of the separately maintained, non-authoritative
+        // `requested_block_heights` counter.
+        let locator_height = self.chain_height();
+        let locator = self.locator();
+        let mut requests = FuturesUnordered::new();
+
+        for _ in 0..OBTAIN_TIPS_FANOUT {
+            let request = self
+                .retry_peer_set
+                .ready_and()
+                .await
+                .map_err(|e| eyre!(e))?
+                .call(zebra_network::Request::FindBlocks {
+                    known_blocks: locator.clone(),
+                    stop: None,
+                });
+
+            requests.push(request);
+        }
+
+        while let Some(response) = requests.next().await {
+            match response.map_err(|e| eyre!(e)) {
+                Ok(zebra_network::Response::BlockHeaderHashes(hashes)) => {
+                    self.queue_new_hashes(hashes, Some(locator_height)).await?
+                }
+                Ok(_) => unreachable!("FindBlocks always gets a BlockHeaderHashes response"),
+                Err(e) => error!("{:?}", e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `ExtendTips` phase: ask for more blocks beyond each candidate tip,
+    /// dropping any tip that's since been found dead instead of extending
+    /// it forever.
+    async fn extend_tips(&mut self) -> Result<(), Report> {
+        let tips: Vec<_> = self.tips.drain().collect();
+
+        for tip in tips {
+            if self.dead_tips.contains(&tip) {
+                continue;
+            }
+
+            let hashes = self
+                .retry_peer_set
+                .ready_and()
+                .await
+                .map_err(|e| eyre!(e))?
+                .call(zebra_network::Request::FindBlocks {
+                    known_blocks: vec![tip],
+                    stop: None,
+                })
+                .await
+                .map_err(|e| eyre!(e))
+                .wrap_err("FindBlocks request failed after retries")
+                .map(|response| match response {
+                    zebra_network::Response::BlockHeaderHashes(hashes) => hashes,
+                    _ => unreachable!("FindBlocks always gets a BlockHeaderHashes response"),
+                });
+
+            match hashes {
+                Ok(hashes) if hashes.is_empty() => {
+                    // Nothing past this tip yet; keep it as a candidate for
+                    // the next round.
+                    self.tips.insert(tip);
+                }
+                // `extend_tips` only knows the tip hash it asked beyond, not
+                // that hash's height, so it can't contribute to observing
+                // the network tip height the way `obtain_tips` can.
+                Ok(hashes) => self.queue_new_hashes(hashes, None).await?,
+                Err(e) => error!("{:?}", e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// De-duplicate a batch of hashes against ones we already know about,
+    /// track the new frontier as a candidate tip, and queue the rest for
+    /// download. `locator_height`, when known, is the height the request
+    /// that produced `hashes` was built from (i.e. our locator's tip), used
+    /// to recognize the real network tip height from a short response.
+    async fn queue_new_hashes(
+        &mut self,
+        hashes: Vec<BlockHeaderHash>,
+        locator_height: Option<BlockHeight>,
+    ) -> Result<(), Report> {
+        // A response shorter than the protocol maximum means the peer has
+        // nothing left beyond its own tip.
+        if let Some(locator_height) = locator_height {
+            if hashes.len() < MAX_FIND_BLOCKS_RESULTS && self.target_height.is_none() {
+                let observed_tip_height = BlockHeight(locator_height.0 + hashes.len() as u32);
+                self.target_height = Some(observed_tip_height);
                 info!(
-                    new_hashes = hashes.len(),
-                    requested = self.requested_block_heights,
-                    in_flight = self.block_requests.len(),
-                    downloaded = self.downloaded_block_heights.len(),
-                    highest = self.downloaded_block_heights.iter().next_back().unwrap().0,
-                    "requested more hashes"
+                    observed_tip_height = observed_tip_height.0,
+                    "observed network tip height"
                 );
-                self.requested_block_heights += hashes.len();
-                hashes
-            })
+            }
+        }
+
+        let new_hashes: Vec<_> = hashes
+            .into_iter()
+            .filter(|hash| self.known_hashes.insert(*hash))
+            .collect();
+
+        let frontier = match new_hashes.last().copied() {
+            Some(frontier) => frontier,
+            None => return Ok(()),
+        };
+        self.tips.insert(frontier);
+
+        self.requested_block_heights += new_hashes.len();
+        info!(
+            new_hashes = new_hashes.len(),
+            requested = self.requested_block_heights,
+            in_flight = self.block_requests.len(),
+            downloaded = self.downloaded_block_heights.len(),
+            tips = self.tips.len(),
+            "queued more hashes"
+        );
+
+        self.request_blocks(frontier, new_hashes).await
     }
 
-    async fn request_blocks(&mut self, hashes: Vec<BlockHeaderHash>) -> Result<(), Report> {
+    async fn request_blocks(
+        &mut self,
+        frontier: BlockHeaderHash,
+        hashes: Vec<BlockHeaderHash>,
+    ) -> Result<(), Report> {
         for chunk in hashes.chunks(10usize) {
-            let request = self.peer_set.ready_and().await.map_err(|e| eyre!(e))?.call(
-                zebra_network::Request::BlocksByHash(chunk.iter().cloned().collect()),
-            );
+            // Use the retrying service so a transient error or timeout on a
+            // single `BlocksByHash` call doesn't permanently drop the chunk.
+            let mut peer_set = self.retry_peer_set.clone();
+            let request = zebra_network::Request::BlocksByHash(chunk.iter().cloned().collect());
+
+            let timed_request: TimedBlockRequest = Box::pin(async move {
+                let start = Instant::now();
+                let result = async {
+                    let peer_set = peer_set.ready_and().await?;
+                    peer_set.call(request).await
+                }
+                .await;
+
+                (frontier, start.elapsed(), result)
+            });
 
-            self.block_requests.push(request);
+            self.block_requests.push(timed_request);
         }
 
         Ok(())
@@ -211,35 +624,329 @@ where
 
     async fn drain_requests(&mut self, request_goal: usize) -> Result<(), Report> {
         while self.block_requests.len() > request_goal {
-            match self
+            let (frontier, latency, response) = self
                 .block_requests
                 .next()
                 .await
-                .expect("expected: block_requests is never empty")
-                .map_err::<Report, _>(|e| eyre!(e))
-            {
+                .expect("expected: block_requests is never empty");
+
+            match response.map_err::<Report, _>(|e| eyre!(e)) {
                 Ok(zebra_network::Response::Blocks(blocks)) => {
+                    self.record_success(latency);
+
                     for block in blocks {
-                        self.downloaded_block_heights
-                            .insert(block.coinbase_height().unwrap());
-                        self.state
-                            .ready_and()
-                            .await
-                            .map_err(|e| eyre!(e))?
-                            .call(zebra_state::Request::AddBlock { block })
-                            .await
-                            .map_err(|e| eyre!(e))?;
+                        let height = block.coinbase_height().unwrap();
+                        let hash = BlockHeaderHash::from(&block);
+                        self.pending_blocks.insert(
+                            height,
+                            PendingBlock {
+                                frontier,
+                                hash,
+                                block,
+                            },
+                        );
                     }
+
+                    self.drain_pending_blocks().await?;
                 }
                 Ok(_) => continue,
                 Err(e) => {
                     error!("{:?}", e);
+                    self.record_failure();
+                    // The retrying service already exhausted its attempts
+                    // for this request; stop extending the tip it came
+                    // from instead of retrying it forever.
+                    self.dead_tips.insert(frontier);
                 }
             }
         }
 
         Ok(())
     }
+
+    /// Accept buffered blocks in height order as their parents land.
+    /// Downloads race concurrently with no ordering guarantee, so a later
+    /// height often arrives before the one it chains from; rather than
+    /// rejecting it outright as unverifiable, we hold it in
+    /// `pending_blocks` until `accepted` catches up to it.
+    async fn drain_pending_blocks(&mut self) -> Result<(), Report> {
+        loop {
+            let next_height = BlockHeight(
+                self.accepted
+                    .keys()
+                    .next_back()
+                    .map_or(0, |height| height.0 + 1),
+            );
+
+            let pending = match self.pending_blocks.remove(&next_height) {
+                Some(pending) => pending,
+                None => break,
+            };
+
+            if let Err(e) = self.verify_block(&pending.block, next_height) {
+                error!("{:?}", e);
+                // Key by the tip this block's chain extends, not the
+                // block's own hash, so `extend_tips`'s dead-tip filter
+                // (keyed by `tips` entries) actually stops extending it.
+                self.dead_tips.insert(pending.frontier);
+                continue;
+            }
+
+            self.accepted.insert(next_height, pending.hash);
+            self.downloaded_block_heights.insert(next_height);
+            self.state
+                .ready_and()
+                .await
+                .map_err(|e| eyre!(e))?
+                .call(zebra_state::Request::AddBlock {
+                    block: pending.block,
+                })
+                .await
+                .map_err(|e| eyre!(e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Grow the window and update the latency EWMA after a successful
+    /// response, then surface the pipeline's self-tuned state.
+    fn record_success(&mut self, latency: Duration) {
+        self.successes += 1;
+        self.ewma_latency_ms = update_ewma(
+            self.ewma_latency_ms,
+            latency.as_millis() as f64,
+            self.successes == 1,
+        );
+        self.window = grow_window(self.window);
+
+        self.trace_window();
+    }
+
+    /// Shrink the window multiplicatively after a failed or errored
+    /// response, so a burst of failures backs off fast.
+    fn record_failure(&mut self) {
+        self.failures += 1;
+        self.window = shrink_window(self.window);
+
+        self.trace_window();
+    }
+
+    fn trace_window(&self) {
+        let total = self.successes + self.failures;
+        let success_rate = if total == 0 {
+            1.0
+        } else {
+            self.successes as f64 / total as f64
+        };
+
+        info!(
+            window = self.window,
+            success_rate,
+            ewma_latency_ms = self.ewma_latency_ms,
+            "adaptive window"
+        );
+    }
+
+    /// Check that `block` chains from the block we accepted at `height - 1`,
+    /// and that it matches any checkpoint hash recorded for `height`.
+    fn verify_block(
+        &self,
+        block: &zebra_chain::block::Block,
+        height: BlockHeight,
+    ) -> Result<(), Report> {
+        verify_linkage(&self.accepted, height, block.header.previous_block_hash)?;
+        verify_checkpoint(self.network, height, BlockHeaderHash::from(block))?;
+
+        Ok(())
+    }
+}
+
+/// Check that `previous_hash` is the hash we accepted at `height - 1`.
+/// Height 0 has no parent to check.
+fn verify_linkage(
+    accepted: &BTreeMap<BlockHeight, BlockHeaderHash>,
+    height: BlockHeight,
+    previous_hash: BlockHeaderHash,
+) -> Result<(), Report> {
+    if height.0 == 0 {
+        return Ok(());
+    }
+
+    match accepted.get(&BlockHeight(height.0 - 1)) {
+        Some(previous) if *previous == previous_hash => Ok(()),
+        Some(_) => Err(eyre!(
+            "block at height {} does not chain from the last accepted block",
+            height.0
+        )),
+        None => Err(eyre!(
+            "block at height {} arrived before its parent was accepted",
+            height.0
+        )),
+    }
+}
+
+/// Check that `hash` matches any checkpoint recorded for `height` on
+/// `network`. Heights without a checkpoint always pass.
+fn verify_checkpoint(
+    network: Network,
+    height: BlockHeight,
+    hash: BlockHeaderHash,
+) -> Result<(), Report> {
+    if let Some((_, expected)) = checkpoints(network)
+        .iter()
+        .find(|(checkpoint_height, _)| *checkpoint_height == height)
+    {
+        if hash != *expected {
+            return Err(eyre!(
+                "block at height {} does not match checkpoint hash",
+                height.0
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Wait for SIGINT or SIGTERM, so `Core::run` can shut down gracefully
+/// instead of being killed mid-write to `zebra_state`.
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigint = signal(SignalKind::interrupt()).expect("failed to install SIGINT handler");
+    let mut sigterm = signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
+
+    tokio::select! {
+        _ = sigint.recv() => {}
+        _ = sigterm.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    tokio::signal::ctrl_c()
+        .await
+        .expect("failed to install Ctrl-C handler");
 }
 
 type Error = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(byte: u8) -> BlockHeaderHash {
+        BlockHeaderHash([byte; 32])
+    }
+
+    #[test]
+    fn exponential_heights_always_ends_at_genesis() {
+        let heights = exponential_heights(BlockHeight(100));
+        assert_eq!(heights.last(), Some(&BlockHeight(0)));
+    }
+
+    #[test]
+    fn exponential_heights_of_genesis_is_just_genesis() {
+        assert_eq!(exponential_heights(BlockHeight(0)), vec![BlockHeight(0)]);
+    }
+
+    #[test]
+    fn exponential_heights_spacing_doubles_each_step() {
+        // 10, 9, 7, 3 (step 1, 2, 4, then saturating to 0)
+        assert_eq!(
+            exponential_heights(BlockHeight(10)),
+            vec![
+                BlockHeight(10),
+                BlockHeight(9),
+                BlockHeight(7),
+                BlockHeight(3),
+                BlockHeight(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn exponential_heights_is_strictly_decreasing() {
+        let heights = exponential_heights(BlockHeight(1_000));
+        for pair in heights.windows(2) {
+            assert!(pair[0].0 > pair[1].0);
+        }
+    }
+
+    #[test]
+    fn verify_linkage_accepts_genesis_with_no_parent() {
+        let accepted = BTreeMap::new();
+        assert!(verify_linkage(&accepted, BlockHeight(0), hash(0)).is_ok());
+    }
+
+    #[test]
+    fn verify_linkage_accepts_matching_parent() {
+        let mut accepted = BTreeMap::new();
+        accepted.insert(BlockHeight(9), hash(9));
+
+        assert!(verify_linkage(&accepted, BlockHeight(10), hash(9)).is_ok());
+    }
+
+    #[test]
+    fn verify_linkage_rejects_mismatched_parent() {
+        let mut accepted = BTreeMap::new();
+        accepted.insert(BlockHeight(9), hash(9));
+
+        assert!(verify_linkage(&accepted, BlockHeight(10), hash(0xff)).is_err());
+    }
+
+    #[test]
+    fn verify_linkage_rejects_missing_parent() {
+        let accepted = BTreeMap::new();
+        assert!(verify_linkage(&accepted, BlockHeight(10), hash(9)).is_err());
+    }
+
+    #[test]
+    fn verify_checkpoint_accepts_matching_genesis() {
+        assert!(verify_checkpoint(Network::Mainnet, BlockHeight(0), GENESIS).is_ok());
+    }
+
+    #[test]
+    fn verify_checkpoint_rejects_wrong_genesis() {
+        assert!(verify_checkpoint(Network::Mainnet, BlockHeight(0), hash(0xff)).is_err());
+    }
+
+    #[test]
+    fn verify_checkpoint_ignores_non_checkpoint_heights() {
+        assert!(verify_checkpoint(Network::Mainnet, BlockHeight(123), hash(0xff)).is_ok());
+    }
+
+    #[test]
+    fn grow_window_adds_growth_step() {
+        assert_eq!(grow_window(100), 100 + WINDOW_GROWTH_STEP);
+    }
+
+    #[test]
+    fn grow_window_clamps_at_max() {
+        assert_eq!(grow_window(MAX_WINDOW), MAX_WINDOW);
+        assert_eq!(grow_window(MAX_WINDOW - 1), MAX_WINDOW);
+    }
+
+    #[test]
+    fn shrink_window_halves() {
+        assert_eq!(shrink_window(1000), 500);
+    }
+
+    #[test]
+    fn shrink_window_clamps_at_min() {
+        assert_eq!(shrink_window(MIN_WINDOW), MIN_WINDOW);
+        assert_eq!(shrink_window(MIN_WINDOW + 1), MIN_WINDOW);
+    }
+
+    #[test]
+    fn update_ewma_seeds_from_first_sample() {
+        assert_eq!(update_ewma(0.0, 250.0, true), 250.0);
+    }
+
+    #[test]
+    fn update_ewma_blends_subsequent_samples() {
+        let blended = update_ewma(100.0, 200.0, false);
+        assert_eq!(blended, EWMA_ALPHA * 200.0 + (1.0 - EWMA_ALPHA) * 100.0);
+        assert!(blended > 100.0 && blended < 200.0);
+    }
+}